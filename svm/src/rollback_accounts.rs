@@ -2,59 +2,70 @@ use {
     crate::nonce_info::NonceInfo,
     solana_account::{AccountSharedData, ReadableAccount, WritableAccount},
     solana_clock::Epoch,
+    solana_nonce::{state::State as NonceState, versions::Versions as NonceVersions},
     solana_pubkey::Pubkey,
+    solana_reward_info::{RewardInfo, RewardType},
     solana_transaction_context::TransactionAccount,
+    std::collections::HashMap,
 };
 
+/// Rent collected for a single account captured by `RollbackAccounts`, so
+/// that rent collected during loading still surfaces as a reward even when
+/// the transaction that loaded the account later fails.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub struct RentDebit {
+    pub rent_collected: u64,
+    pub post_balance: u64,
+}
+
+impl RentDebit {
+    fn try_into_reward_info(self) -> Option<RewardInfo> {
+        let rent_collected = i64::try_from(self.rent_collected).ok()?;
+        (rent_collected > 0).then(|| RewardInfo {
+            reward_type: RewardType::Rent,
+            lamports: rent_collected.saturating_neg(),
+            post_balance: self.post_balance,
+            commission: None,
+        })
+    }
+}
+
 /// Captured account state used to rollback account state for nonce and fee
 /// payer accounts after a failed executed transaction.
+///
+/// Accounts are stored in a flat, key-deduplicated set rather than a
+/// fixed-shape enum: when the nonce account and the fee payer are the same
+/// address, they collapse into a single entry, mirroring how the runtime's
+/// `collect_accounts_to_store` deduplicates writable accounts by key. This
+/// also leaves room to track additional rolled-back accounts in the future
+/// without adding new cases to match on.
 #[derive(PartialEq, Eq, Debug, Clone)]
-pub enum RollbackAccounts {
-    FeePayerOnly {
-        fee_payer: TransactionAccount,
-    },
-    SameNonceAndFeePayer {
-        nonce: TransactionAccount,
-    },
-    SeparateNonceAndFeePayer {
-        nonce: TransactionAccount,
-        fee_payer: TransactionAccount,
-    },
+pub struct RollbackAccounts {
+    accounts: Vec<TransactionAccount>,
+    /// Index into `accounts` of the fee payer.
+    fee_payer_index: usize,
+    /// Index into `accounts` of the nonce account, if a durable nonce was
+    /// used. Equal to `fee_payer_index` when the nonce account and the fee
+    /// payer are the same address.
+    nonce_index: Option<usize>,
+    rent_debits: HashMap<Pubkey, RentDebit>,
 }
 
 #[cfg(feature = "dev-context-only-utils")]
 impl Default for RollbackAccounts {
     fn default() -> Self {
-        Self::FeePayerOnly {
-            fee_payer: TransactionAccount::default(),
+        Self {
+            accounts: vec![TransactionAccount::default()],
+            fee_payer_index: 0,
+            nonce_index: None,
+            rent_debits: HashMap::default(),
         }
     }
 }
 
-/// Rollback accounts iterator.
-/// This struct is created by the `RollbackAccounts::iter`.
-pub struct RollbackAccountsIter<'a> {
-    fee_payer: Option<&'a TransactionAccount>,
-    nonce: Option<&'a TransactionAccount>,
-}
-
-impl<'a> Iterator for RollbackAccountsIter<'a> {
-    type Item = &'a TransactionAccount;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        if let Some(fee_payer) = self.fee_payer.take() {
-            return Some(fee_payer);
-        }
-        if let Some(nonce) = self.nonce.take() {
-            return Some(nonce);
-        }
-        None
-    }
-}
-
 impl<'a> IntoIterator for &'a RollbackAccounts {
     type Item = &'a TransactionAccount;
-    type IntoIter = RollbackAccountsIter<'a>;
+    type IntoIter = std::slice::Iter<'a, TransactionAccount>;
 
     fn into_iter(self) -> Self::IntoIter {
         self.iter()
@@ -67,7 +78,13 @@ impl RollbackAccounts {
         fee_payer_address: Pubkey,
         mut fee_payer_account: AccountSharedData,
         fee_payer_loaded_rent_epoch: Epoch,
+        rent_epoch_rollback_feature_active: bool,
+        fee_payer_rent_debit: RentDebit,
+        nonce_rent_debit: RentDebit,
     ) -> Self {
+        let mut rent_debits = HashMap::with_capacity(2);
+        rent_debits.insert(fee_payer_address, fee_payer_rent_debit);
+
         if let Some(nonce) = nonce {
             if &fee_payer_address == nonce.address() {
                 // `nonce` contains an AccountSharedData which has already been advanced to the current DurableNonce
@@ -76,53 +93,69 @@ impl RollbackAccounts {
                 // so we capture both the data change for the nonce and the lamports/rent epoch change for the fee payer
                 fee_payer_account.set_data_from_slice(nonce.account().data());
 
-                RollbackAccounts::SameNonceAndFeePayer {
-                    nonce: (fee_payer_address, fee_payer_account),
+                // With `rent_epoch_rollback_feature_active`, the fee payer's rent
+                // epoch change is not kept: it is reset to its loaded value.
+                if rent_epoch_rollback_feature_active {
+                    fee_payer_account.set_rent_epoch(fee_payer_loaded_rent_epoch);
+                }
+
+                Self {
+                    accounts: vec![(fee_payer_address, fee_payer_account)],
+                    fee_payer_index: 0,
+                    nonce_index: Some(0),
+                    rent_debits,
                 }
             } else {
-                RollbackAccounts::SeparateNonceAndFeePayer {
-                    nonce: (nonce.address, nonce.account),
-                    fee_payer: (fee_payer_address, fee_payer_account),
+                if rent_epoch_rollback_feature_active {
+                    fee_payer_account.set_rent_epoch(fee_payer_loaded_rent_epoch);
+                }
+                rent_debits.insert(nonce.address, nonce_rent_debit);
+
+                Self {
+                    accounts: vec![
+                        (fee_payer_address, fee_payer_account),
+                        (nonce.address, nonce.account),
+                    ],
+                    fee_payer_index: 0,
+                    nonce_index: Some(1),
+                    rent_debits,
                 }
             }
         } else {
             // When rolling back failed transactions which don't use nonces, the
             // runtime should not update the fee payer's rent epoch so reset the
             // rollback fee payer account's rent epoch to its originally loaded
-            // rent epoch value. In the future, a feature gate could be used to
-            // alter this behavior such that rent epoch updates are handled the
-            // same for both nonce and non-nonce failed transactions.
+            // rent epoch value. When `rent_epoch_rollback_feature_active` is
+            // set, the same reset is applied to the fee payer in the
+            // nonce-using paths above as well, making rent epoch rollback
+            // behavior consistent regardless of transaction kind.
             fee_payer_account.set_rent_epoch(fee_payer_loaded_rent_epoch);
-            RollbackAccounts::FeePayerOnly {
-                fee_payer: (fee_payer_address, fee_payer_account),
+            Self {
+                accounts: vec![(fee_payer_address, fee_payer_account)],
+                fee_payer_index: 0,
+                nonce_index: None,
+                rent_debits,
             }
         }
     }
 
     /// Number of accounts tracked for rollback
     pub fn count(&self) -> usize {
-        match self {
-            Self::FeePayerOnly { .. } | Self::SameNonceAndFeePayer { .. } => 1,
-            Self::SeparateNonceAndFeePayer { .. } => 2,
-        }
+        self.accounts.len()
     }
 
     /// Iterator over accounts tracked for rollback.
-    pub fn iter(&self) -> RollbackAccountsIter<'_> {
-        match self {
-            Self::FeePayerOnly { fee_payer } => RollbackAccountsIter {
-                fee_payer: Some(fee_payer),
-                nonce: None,
-            },
-            Self::SameNonceAndFeePayer { nonce } => RollbackAccountsIter {
-                fee_payer: None,
-                nonce: Some(nonce),
-            },
-            Self::SeparateNonceAndFeePayer { nonce, fee_payer } => RollbackAccountsIter {
-                fee_payer: Some(fee_payer),
-                nonce: Some(nonce),
-            },
-        }
+    pub fn iter(&self) -> std::slice::Iter<'_, TransactionAccount> {
+        self.accounts.iter()
+    }
+
+    /// Rent rewards accrued for the accounts tracked for rollback, for
+    /// inclusion in the transaction's reward list even though the
+    /// transaction itself failed.
+    pub fn into_unordered_rewards_iter(self) -> impl Iterator<Item = (Pubkey, RewardInfo)> {
+        self.rent_debits
+            .into_iter()
+            .filter_map(|(pubkey, rent_debit)| Some((pubkey, rent_debit.try_into_reward_info()?)))
     }
 
     /// Size of accounts tracked for rollback, used when calculating the actual
@@ -134,6 +167,28 @@ impl RollbackAccounts {
         }
         total_size
     }
+
+    /// The nonce account captured for rollback, if this transaction used a
+    /// durable nonce.
+    pub fn nonce_account(&self) -> Option<&TransactionAccount> {
+        self.nonce_index.map(|index| &self.accounts[index])
+    }
+
+    /// The fee payer account captured for rollback.
+    pub fn fee_payer_account(&self) -> &TransactionAccount {
+        &self.accounts[self.fee_payer_index]
+    }
+
+    /// The `lamports_per_signature` stored in the captured nonce account's
+    /// state, if this transaction used an initialized durable nonce.
+    pub fn lamports_per_signature(&self) -> Option<u64> {
+        let (_, nonce_account) = self.nonce_account()?;
+        let nonce_versions = bincode::deserialize::<NonceVersions>(nonce_account.data()).ok()?;
+        match nonce_versions.state() {
+            NonceState::Initialized(data) => Some(data.get_lamports_per_signature()),
+            NonceState::Uninitialized => None,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -167,15 +222,15 @@ mod tests {
             fee_payer_address,
             rent_epoch_updated_fee_payer_account,
             fee_payer_rent_epoch,
+            false,
+            RentDebit::default(),
+            RentDebit::default(),
         );
 
         let expected_fee_payer = (fee_payer_address, fee_payer_account);
-        match rollback_accounts {
-            RollbackAccounts::FeePayerOnly { fee_payer } => {
-                assert_eq!(expected_fee_payer, fee_payer);
-            }
-            _ => panic!("Expected FeePayerOnly variant"),
-        }
+        assert_eq!(rollback_accounts.count(), 1);
+        assert_eq!(rollback_accounts.nonce_account(), None);
+        assert_eq!(rollback_accounts.fee_payer_account(), &expected_fee_payer);
     }
 
     #[test]
@@ -206,13 +261,60 @@ mod tests {
             nonce_address,
             rent_epoch_updated_fee_payer_account,
             u64::MAX, // ignored
+            false,
+            RentDebit::default(),
+            RentDebit::default(),
         );
 
-        let expected_rollback_accounts = RollbackAccounts::SameNonceAndFeePayer {
-            nonce: (nonce_address, nonce_account),
+        let expected_account = (nonce_address, nonce_account);
+        assert_eq!(rollback_accounts.count(), 1);
+        assert_eq!(rollback_accounts.nonce_account(), Some(&expected_account));
+        assert_eq!(rollback_accounts.fee_payer_account(), &expected_account);
+    }
+
+    #[test]
+    fn test_new_same_nonce_and_fee_payer_rent_epoch_rollback_feature() {
+        let nonce_address = Pubkey::new_unique();
+        let durable_nonce = DurableNonce::from_blockhash(&Hash::new_unique());
+        let lamports_per_signature = 42;
+        let fee_payer_rent_epoch = 1;
+        let nonce_account = AccountSharedData::new_data(
+            43,
+            &NonceVersions::new(NonceState::Initialized(NonceData::new(
+                Pubkey::default(),
+                durable_nonce,
+                lamports_per_signature,
+            ))),
+            &system_program::id(),
+        )
+        .unwrap();
+
+        let rent_epoch_updated_fee_payer_account = {
+            let mut account = nonce_account.clone();
+            account.set_lamports(nonce_account.lamports());
+            account.set_rent_epoch(fee_payer_rent_epoch + 1);
+            account
         };
 
-        assert_eq!(expected_rollback_accounts, rollback_accounts);
+        let nonce = NonceInfo::new(nonce_address, rent_epoch_updated_fee_payer_account.clone());
+        let rollback_accounts = RollbackAccounts::new(
+            Some(nonce),
+            nonce_address,
+            rent_epoch_updated_fee_payer_account,
+            fee_payer_rent_epoch,
+            true,
+            RentDebit::default(),
+            RentDebit::default(),
+        );
+
+        let expected_account = {
+            let mut account = nonce_account;
+            account.set_rent_epoch(fee_payer_rent_epoch);
+            (nonce_address, account)
+        };
+        assert_eq!(rollback_accounts.count(), 1);
+        assert_eq!(rollback_accounts.nonce_account(), Some(&expected_account));
+        assert_eq!(rollback_accounts.fee_payer_account(), &expected_account);
     }
 
     #[test]
@@ -246,16 +348,266 @@ mod tests {
             fee_payer_address,
             rent_epoch_updated_fee_payer_account.clone(),
             u64::MAX, // ignored
+            false,
+            RentDebit::default(),
+            RentDebit::default(),
         );
 
         let expected_nonce = (nonce_address, nonce_account);
         let expected_fee_payer = (fee_payer_address, fee_payer_account);
-        match rollback_accounts {
-            RollbackAccounts::SeparateNonceAndFeePayer { nonce, fee_payer } => {
-                assert_eq!(expected_nonce, nonce);
-                assert_eq!(expected_fee_payer, fee_payer);
-            }
-            _ => panic!("Expected SeparateNonceAndFeePayer variant"),
-        }
+        assert_eq!(rollback_accounts.count(), 2);
+        assert_eq!(rollback_accounts.nonce_account(), Some(&expected_nonce));
+        assert_eq!(rollback_accounts.fee_payer_account(), &expected_fee_payer);
+    }
+
+    #[test]
+    fn test_separate_nonce_and_fee_payer_rent_epoch_rollback_feature() {
+        let nonce_address = Pubkey::new_unique();
+        let durable_nonce = DurableNonce::from_blockhash(&Hash::new_unique());
+        let lamports_per_signature = 42;
+        let nonce_account = AccountSharedData::new_data(
+            43,
+            &NonceVersions::new(NonceState::Initialized(NonceData::new(
+                Pubkey::default(),
+                durable_nonce,
+                lamports_per_signature,
+            ))),
+            &system_program::id(),
+        )
+        .unwrap();
+
+        let fee_payer_address = Pubkey::new_unique();
+        let fee_payer_account = AccountSharedData::new(44, 0, &Pubkey::default());
+        let fee_payer_rent_epoch = 1;
+
+        let rent_epoch_updated_fee_payer_account = {
+            let mut account = fee_payer_account.clone();
+            account.set_lamports(fee_payer_account.lamports());
+            account.set_rent_epoch(fee_payer_rent_epoch + 1);
+            account
+        };
+
+        let nonce = NonceInfo::new(nonce_address, nonce_account.clone());
+        let rollback_accounts = RollbackAccounts::new(
+            Some(nonce),
+            fee_payer_address,
+            rent_epoch_updated_fee_payer_account,
+            fee_payer_rent_epoch,
+            true,
+            RentDebit::default(),
+            RentDebit::default(),
+        );
+
+        let expected_nonce = (nonce_address, nonce_account);
+        let expected_fee_payer = {
+            let mut account = fee_payer_account;
+            account.set_rent_epoch(fee_payer_rent_epoch);
+            (fee_payer_address, account)
+        };
+        assert_eq!(rollback_accounts.count(), 2);
+        assert_eq!(rollback_accounts.nonce_account(), Some(&expected_nonce));
+        assert_eq!(rollback_accounts.fee_payer_account(), &expected_fee_payer);
+    }
+
+    #[test]
+    fn test_into_unordered_rewards_iter() {
+        let nonce_address = Pubkey::new_unique();
+        let durable_nonce = DurableNonce::from_blockhash(&Hash::new_unique());
+        let nonce_account = AccountSharedData::new_data(
+            43,
+            &NonceVersions::new(NonceState::Initialized(NonceData::new(
+                Pubkey::default(),
+                durable_nonce,
+                42,
+            ))),
+            &system_program::id(),
+        )
+        .unwrap();
+        let fee_payer_address = Pubkey::new_unique();
+        let fee_payer_account = AccountSharedData::new(44, 0, &Pubkey::default());
+
+        let nonce = NonceInfo::new(nonce_address, nonce_account);
+        let rollback_accounts = RollbackAccounts::new(
+            Some(nonce),
+            fee_payer_address,
+            fee_payer_account,
+            u64::MAX, // ignored
+            false,
+            RentDebit {
+                rent_collected: 7,
+                post_balance: 44,
+            },
+            RentDebit {
+                rent_collected: 0,
+                post_balance: 43,
+            },
+        );
+
+        let rewards: HashMap<_, _> = rollback_accounts.into_unordered_rewards_iter().collect();
+        assert_eq!(rewards.len(), 1);
+        let fee_payer_reward = rewards.get(&fee_payer_address).unwrap();
+        assert_eq!(fee_payer_reward.lamports, -7);
+        assert_eq!(fee_payer_reward.post_balance, 44);
+        assert!(!rewards.contains_key(&nonce_address));
+    }
+
+    #[test]
+    fn test_fee_payer_only_accessors() {
+        let fee_payer_address = Pubkey::new_unique();
+        let fee_payer_account = AccountSharedData::new(100, 0, &Pubkey::default());
+
+        let rollback_accounts = RollbackAccounts::new(
+            None,
+            fee_payer_address,
+            fee_payer_account.clone(),
+            fee_payer_account.rent_epoch(),
+            false,
+            RentDebit::default(),
+            RentDebit::default(),
+        );
+
+        assert_eq!(rollback_accounts.nonce_account(), None);
+        assert_eq!(
+            rollback_accounts.fee_payer_account(),
+            &(fee_payer_address, fee_payer_account)
+        );
+        assert_eq!(rollback_accounts.lamports_per_signature(), None);
+    }
+
+    #[test]
+    fn test_separate_nonce_and_fee_payer_accessors() {
+        let nonce_address = Pubkey::new_unique();
+        let durable_nonce = DurableNonce::from_blockhash(&Hash::new_unique());
+        let lamports_per_signature = 42;
+        let nonce_account = AccountSharedData::new_data(
+            43,
+            &NonceVersions::new(NonceState::Initialized(NonceData::new(
+                Pubkey::default(),
+                durable_nonce,
+                lamports_per_signature,
+            ))),
+            &system_program::id(),
+        )
+        .unwrap();
+
+        let fee_payer_address = Pubkey::new_unique();
+        let fee_payer_account = AccountSharedData::new(44, 0, &Pubkey::default());
+
+        let nonce = NonceInfo::new(nonce_address, nonce_account.clone());
+        let rollback_accounts = RollbackAccounts::new(
+            Some(nonce),
+            fee_payer_address,
+            fee_payer_account.clone(),
+            u64::MAX, // ignored
+            false,
+            RentDebit::default(),
+            RentDebit::default(),
+        );
+
+        assert_eq!(
+            rollback_accounts.nonce_account(),
+            Some(&(nonce_address, nonce_account))
+        );
+        assert_eq!(
+            rollback_accounts.fee_payer_account(),
+            &(fee_payer_address, fee_payer_account)
+        );
+        assert_eq!(
+            rollback_accounts.lamports_per_signature(),
+            Some(lamports_per_signature)
+        );
+    }
+
+    #[test]
+    fn test_same_address_nonce_and_fee_payer_dedupe_into_single_entry() {
+        let address = Pubkey::new_unique();
+        let durable_nonce = DurableNonce::from_blockhash(&Hash::new_unique());
+        let nonce_account = AccountSharedData::new_data(
+            43,
+            &NonceVersions::new(NonceState::Initialized(NonceData::new(
+                Pubkey::default(),
+                durable_nonce,
+                42,
+            ))),
+            &system_program::id(),
+        )
+        .unwrap();
+
+        let nonce = NonceInfo::new(address, nonce_account);
+        let rollback_accounts = RollbackAccounts::new(
+            Some(nonce),
+            address,
+            AccountSharedData::new(100, 0, &Pubkey::default()),
+            u64::MAX, // ignored
+            false,
+            RentDebit::default(),
+            RentDebit::default(),
+        );
+
+        assert_eq!(rollback_accounts.count(), 1);
+        assert_eq!(rollback_accounts.iter().count(), 1);
+    }
+
+    #[test]
+    fn test_distinct_nonce_and_fee_payer_addresses_produce_two_entries() {
+        let nonce_address = Pubkey::new_unique();
+        let durable_nonce = DurableNonce::from_blockhash(&Hash::new_unique());
+        let nonce_account = AccountSharedData::new_data(
+            43,
+            &NonceVersions::new(NonceState::Initialized(NonceData::new(
+                Pubkey::default(),
+                durable_nonce,
+                42,
+            ))),
+            &system_program::id(),
+        )
+        .unwrap();
+        let fee_payer_address = Pubkey::new_unique();
+
+        let nonce = NonceInfo::new(nonce_address, nonce_account);
+        let rollback_accounts = RollbackAccounts::new(
+            Some(nonce),
+            fee_payer_address,
+            AccountSharedData::new(100, 0, &Pubkey::default()),
+            u64::MAX, // ignored
+            false,
+            RentDebit::default(),
+            RentDebit::default(),
+        );
+
+        assert_eq!(rollback_accounts.count(), 2);
+        assert_eq!(rollback_accounts.iter().count(), 2);
+    }
+
+    #[test]
+    fn test_iter_order_is_fee_payer_then_nonce() {
+        let nonce_address = Pubkey::new_unique();
+        let durable_nonce = DurableNonce::from_blockhash(&Hash::new_unique());
+        let nonce_account = AccountSharedData::new_data(
+            43,
+            &NonceVersions::new(NonceState::Initialized(NonceData::new(
+                Pubkey::default(),
+                durable_nonce,
+                42,
+            ))),
+            &system_program::id(),
+        )
+        .unwrap();
+        let fee_payer_address = Pubkey::new_unique();
+        let fee_payer_account = AccountSharedData::new(100, 0, &Pubkey::default());
+
+        let nonce = NonceInfo::new(nonce_address, nonce_account);
+        let rollback_accounts = RollbackAccounts::new(
+            Some(nonce),
+            fee_payer_address,
+            fee_payer_account,
+            u64::MAX, // ignored
+            false,
+            RentDebit::default(),
+            RentDebit::default(),
+        );
+
+        let addresses: Vec<Pubkey> = rollback_accounts.iter().map(|(pubkey, _)| *pubkey).collect();
+        assert_eq!(addresses, vec![fee_payer_address, nonce_address]);
     }
 }